@@ -1,7 +1,130 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Wire protocol spoken between the engine and a bot client.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Protocol {
+    /// The original whitespace-separated protocol (`ready`, bare numbers, ...).
+    #[default]
+    Legacy,
+    /// Newline-delimited JSON messages, see `client::ServerMessage`/`client::ClientMessage`.
+    Json,
+}
+
+/// Confinement applied to bots run via `Config::image` (docker). Only consulted when
+/// a bot is actually sandboxed in a container; host-run bots are unaffected.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Sandbox {
+    /// Whether the container may reach the network. Off by default so bots
+    /// cannot exfiltrate data or phone home.
+    #[serde(default)]
+    pub(crate) network: bool,
+    /// `docker run --memory` value, e.g. `"512m"`.
+    #[serde(default = "Sandbox::default_memory")]
+    pub(crate) memory: String,
+    /// `docker run --memory-swap` value.
+    #[serde(default = "Sandbox::default_memory_swap")]
+    pub(crate) memory_swap: String,
+    /// `docker run --cpus` value, e.g. `"1.0"`.
+    #[serde(default = "Sandbox::default_cpus")]
+    pub(crate) cpus: String,
+    /// `docker run --pids-limit` value, guards against fork bombs.
+    #[serde(default = "Sandbox::default_pids_limit")]
+    pub(crate) pids_limit: u32,
+    /// Optional path to a seccomp profile JSON file.
+    #[serde(default)]
+    pub(crate) seccomp_profile: Option<String>,
+}
+
+impl Sandbox {
+    fn default_memory() -> String {
+        "512m".to_string()
+    }
+
+    fn default_memory_swap() -> String {
+        "512m".to_string()
+    }
+
+    fn default_cpus() -> String {
+        "1.0".to_string()
+    }
+
+    fn default_pids_limit() -> u32 {
+        64
+    }
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            network: false,
+            memory: Self::default_memory(),
+            memory_swap: Self::default_memory_swap(),
+            cpus: Self::default_cpus(),
+            pids_limit: Self::default_pids_limit(),
+            seccomp_profile: None,
+        }
+    }
+}
+
+fn default_game() -> String {
+    "lowest-unique".to_string()
+}
+
+/// How to run a bot of a given source kind. `run` (and `build`, if present)
+/// are argv templates where the literal tokens `{script}` and `{out}` are
+/// substituted with the bot's script path and build artifact path.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct Runner {
+    #[serde(default)]
+    pub(crate) build: Option<Vec<String>>,
+    pub(crate) run: Vec<String>,
+}
+
+/// Maps a file extension (without the leading dot) to the `Runner` used to
+/// execute it. Consulted by `runner::detect_runner` for bots run on the host.
+pub(crate) type Runners = HashMap<String, Runner>;
+
+pub(crate) fn default_runners() -> Runners {
+    let mut runners = Runners::new();
+    runners.insert(
+        "py".to_string(),
+        Runner {
+            build: None,
+            run: vec!["python3".to_string(), "{script}".to_string()],
+        },
+    );
+    runners.insert(
+        "js".to_string(),
+        Runner {
+            build: None,
+            run: vec!["node".to_string(), "{script}".to_string()],
+        },
+    );
+    runners.insert(
+        "rb".to_string(),
+        Runner {
+            build: None,
+            run: vec!["ruby".to_string(), "{script}".to_string()],
+        },
+    );
+    runners
+}
+
 #[derive(Deserialize, Debug)]
 pub(crate) struct Config {
     pub(crate) programs: Vec<String>,
     pub(crate) rounds: u32,
     pub(crate) image: Option<String>,
+    #[serde(default)]
+    pub(crate) protocol: Protocol,
+    #[serde(default)]
+    pub(crate) sandbox: Sandbox,
+    /// Name of the `Game` to host, looked up via `game::by_name`.
+    #[serde(default = "default_game")]
+    pub(crate) game: String,
+    /// Extension -> interpreter mapping used to run bots on the host.
+    #[serde(default = "default_runners")]
+    pub(crate) runners: Runners,
 }
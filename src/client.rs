@@ -1,11 +1,37 @@
+use crate::cfg::{Protocol, Runners, Sandbox};
+use crate::game::Game;
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
-use std::io::{BufRead, Write};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
 use std::path::Path;
 use std::process::Stdio;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+
+/// Version of the JSON protocol this engine speaks.
+const JSON_PROTOCOL_VERSION: u32 = 1;
+
+/// Messages sent by the engine to a client speaking [`Protocol::Json`].
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Hello { protocol: u32, game: &'static str },
+    Move,
+    Result { nums: Vec<u32> },
+    End,
+}
+
+/// Messages sent by a client speaking [`Protocol::Json`] back to the engine.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Ready { protocol: u32 },
+    Move { value: u32 },
+}
 
 #[derive(Eq, PartialEq, Debug)]
 enum State {
@@ -24,18 +50,14 @@ enum State {
 }
 #[derive(Debug)]
 pub(crate) struct Client {
-    child: std::process::Child,
-    stdout: Arc<Mutex<std::io::BufReader<std::process::ChildStdout>>>,
-    stdin: Arc<Mutex<std::io::BufWriter<std::process::ChildStdin>>>,
+    child: tokio::process::Child,
+    stdout: Arc<Mutex<BufReader<tokio::process::ChildStdout>>>,
+    stdin: Arc<Mutex<tokio::process::ChildStdin>>,
     name: String,
     state: State,
     num: u32,
-}
-
-struct ReadLineState {
-    buf: String,
-    done: bool,
-    error: bool,
+    protocol: Protocol,
+    game: Arc<dyn Game>,
 }
 
 impl std::fmt::Display for Client {
@@ -46,42 +68,62 @@ impl std::fmt::Display for Client {
 
 impl Drop for Client {
     fn drop(&mut self) {
-        self.child.kill().ok();
-        self.child.wait().ok();
+        // `Child::kill` is async in tokio; `start_kill` is the sync,
+        // Drop-safe way to send a kill signal to a child we no longer care about.
+        self.child.start_kill().ok();
     }
 }
 
 impl Client {
-    fn from_child(mut child: std::process::Child, path: &Path) -> Client {
-        let stdout = Arc::new(Mutex::new(std::io::BufReader::new(
-            child.stdout.take().unwrap(),
-        )));
-        let stdin = Arc::new(Mutex::new(std::io::BufWriter::new(
-            child.stdin.take().unwrap(),
-        )));
-        Client {
+    async fn from_child(
+        mut child: tokio::process::Child,
+        path: &Path,
+        protocol: Protocol,
+        game: Arc<dyn Game>,
+    ) -> Client {
+        let stdout = Arc::new(Mutex::new(BufReader::new(child.stdout.take().unwrap())));
+        let stdin = Arc::new(Mutex::new(child.stdin.take().unwrap()));
+        let mut client = Client {
             child,
             name: path.display().to_string(),
             state: State::Init,
             stdout,
             num: 0xDEADBEEF,
             stdin,
+            protocol,
+            game,
+        };
+        if client.protocol == Protocol::Json {
+            client.send_hello().await;
         }
+        client
     }
 
-    fn new_on_host(path: &str) -> Result<Client> {
-        let child = std::process::Command::new(std::env::current_exe()?)
+    async fn new_on_host(
+        path: &str,
+        protocol: Protocol,
+        runners: &Runners,
+        game: Arc<dyn Game>,
+    ) -> Result<Client> {
+        let child = tokio::process::Command::new(std::env::current_exe()?)
             .arg(path)
             .env("__RUN__", "1")
+            .env("__RUNNERS__", serde_json::to_string(runners)?)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::inherit())
             .spawn()?;
 
-        Ok(Self::from_child(child, std::path::Path::new(path)))
+        Ok(Self::from_child(child, std::path::Path::new(path), protocol, game).await)
     }
 
-    fn new_docker(path: &str, image: &str) -> Result<Client> {
+    async fn new_docker(
+        path: &str,
+        image: &str,
+        protocol: Protocol,
+        sandbox: &Sandbox,
+        game: Arc<dyn Game>,
+    ) -> Result<Client> {
         let mut inner_path = std::path::PathBuf::new();
         inner_path.push("/src");
         let path = std::path::Path::new(path);
@@ -97,12 +139,26 @@ impl Client {
                 .display(),
             inner_path.display()
         );
-        let child = std::process::Command::new("docker")
-            .arg("run")
+        let mut cmd = tokio::process::Command::new("docker");
+        cmd.arg("run")
             .arg("--interactive")
             .arg("--rm")
             .arg("--env=__RUN__=1")
             .arg(mount_flag)
+            .arg(format!(
+                "--network={}",
+                if sandbox.network { "bridge" } else { "none" }
+            ))
+            .arg(format!("--memory={}", sandbox.memory))
+            .arg(format!("--memory-swap={}", sandbox.memory_swap))
+            .arg(format!("--cpus={}", sandbox.cpus))
+            .arg(format!("--pids-limit={}", sandbox.pids_limit))
+            .arg("--cap-drop=ALL")
+            .arg("--security-opt=no-new-privileges");
+        if let Some(profile) = &sandbox.seccomp_profile {
+            cmd.arg(format!("--security-opt=seccomp={}", profile));
+        }
+        let child = cmd
             .arg(image)
             .arg(inner_path)
             .stdin(Stdio::piped())
@@ -110,13 +166,20 @@ impl Client {
             .stderr(Stdio::inherit())
             .spawn()?;
 
-        Ok(Self::from_child(child, path))
+        Ok(Self::from_child(child, path, protocol, game).await)
     }
 
-    pub(crate) fn new(path: &str, image: Option<&str>) -> Result<Client> {
+    pub(crate) async fn new(
+        path: &str,
+        image: Option<&str>,
+        protocol: Protocol,
+        sandbox: &Sandbox,
+        runners: &Runners,
+        game: Arc<dyn Game>,
+    ) -> Result<Client> {
         match image {
-            Some(img) => Self::new_docker(path, img),
-            None => Self::new_on_host(path),
+            Some(img) => Self::new_docker(path, img, protocol, sandbox, game).await,
+            None => Self::new_on_host(path, protocol, runners, game).await,
         }
     }
 
@@ -128,44 +191,25 @@ impl Client {
         &self.name
     }
 
-    fn read_line(&mut self) -> Result<String> {
-        let state = ReadLineState {
-            buf: String::new(),
-            done: false,
-            error: false,
+    async fn read_line(&mut self) -> Result<String> {
+        let timeout = match self.state {
+            State::Init => Duration::from_millis(10000),
+            _ => Duration::from_millis(1000),
         };
-        let state = Arc::new(Mutex::new(state));
         let stdout = Arc::clone(&self.stdout);
-        let ch_state = Arc::clone(&state);
-        let name = self.name.clone();
-        let handle = std::thread::spawn(move || {
-            let state = ch_state;
+        let read = async move {
             let mut buf = String::new();
-            let err = stdout.lock().unwrap().read_line(&mut buf).err();
-            let mut st = state.lock().unwrap();
-            st.buf = buf.trim().to_string();
-            st.done = true;
-            if let Some(err) = err {
-                eprintln!("client {}: i/o error: {}", name, err);
-                st.error = true;
-            }
-        });
-        let timeout_ms = match self.state {
-            State::Init => 10000,
-            _ => 1000,
+            stdout.lock().await.read_line(&mut buf).await?;
+            Ok::<_, std::io::Error>(buf)
         };
-        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
-        loop {
-            let mut st = state.lock().unwrap();
-            if st.done {
-                handle.join().unwrap();
-                if st.error {
-                    self.err();
-                    bail!("reader thread errored");
-                }
-                break Ok(std::mem::take(&mut st.buf));
+        match tokio::time::timeout(timeout, read).await {
+            Ok(Ok(buf)) => Ok(buf.trim().to_string()),
+            Ok(Err(err)) => {
+                eprintln!("client {}: i/o error: {}", &self.name, err);
+                self.err();
+                bail!("i/o error: {}", err);
             }
-            if std::time::Instant::now() > deadline {
+            Err(_) => {
                 self.err();
                 bail!("deadline violated");
             }
@@ -177,16 +221,16 @@ impl Client {
         self.num = u32::max_value();
     }
 
-    fn is_err(&self) -> bool {
+    pub(crate) fn is_err(&self) -> bool {
         self.state == State::Error
     }
 
-    pub(crate) fn poll(&mut self) {
+    pub(crate) async fn poll(&mut self) {
         match self.state {
             State::Error | State::Wait | State::PostStep | State::End => return,
             State::Init | State::Step => (),
         };
-        let line = match self.read_line() {
+        let line = match self.read_line().await {
             Ok(l) => l,
             Err(err) => {
                 println!("client {}: failed to read line: {}", &self.name, err);
@@ -194,6 +238,13 @@ impl Client {
                 return;
             }
         };
+        match self.protocol {
+            Protocol::Legacy => self.poll_legacy(line),
+            Protocol::Json => self.poll_json(&line),
+        }
+    }
+
+    fn poll_legacy(&mut self, line: String) {
         match self.state {
             State::Init => {
                 if line == "ready" {
@@ -207,100 +258,178 @@ impl Client {
                 }
             }
             State::Step => {
-                let guess: u32 = match line.parse() {
-                    Ok(g) => g,
+                let mv = match self.game.validate_move(&line) {
+                    Ok(mv) => mv,
                     Err(err) => {
                         println!(
-                            "client {}: got '{}' which is not a number: {}",
+                            "client {}: got '{}' which is not a valid move: {}",
                             &self.name, line, err
                         );
                         self.err();
                         return;
                     }
                 };
-                self.num = guess;
+                self.num = mv;
                 self.state = State::PostStep;
             }
             _ => unreachable!(),
         }
     }
 
+    fn poll_json(&mut self, line: &str) {
+        let value: serde_json::Value = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(err) => {
+                println!("client {}: invalid json '{}': {}", &self.name, line, err);
+                self.err();
+                return;
+            }
+        };
+        let kind = value.get("type").and_then(|t| t.as_str()).unwrap_or("");
+        match (&self.state, kind) {
+            (State::Init, "ready") => match serde_json::from_value(value) {
+                Ok(ClientMessage::Ready { protocol }) if protocol == JSON_PROTOCOL_VERSION => {
+                    self.state = State::Wait;
+                }
+                Ok(ClientMessage::Ready { protocol }) => {
+                    println!(
+                        "client {}: protocol mismatch: engine speaks {}, client speaks {}",
+                        &self.name, JSON_PROTOCOL_VERSION, protocol
+                    );
+                    self.err();
+                }
+                Err(err) => {
+                    println!(
+                        "client {}: malformed `ready` message: {}",
+                        &self.name, err
+                    );
+                    self.err();
+                }
+                Ok(_) => unreachable!(),
+            },
+            (State::Step, "move") => match serde_json::from_value(value) {
+                Ok(ClientMessage::Move { value }) => match self.game.validate_move(&value.to_string()) {
+                    Ok(mv) => {
+                        self.num = mv;
+                        self.state = State::PostStep;
+                    }
+                    Err(err) => {
+                        println!(
+                            "client {}: move {} rejected by game rules: {}",
+                            &self.name, value, err
+                        );
+                        self.err();
+                    }
+                },
+                Err(err) => {
+                    println!("client {}: malformed `move` message: {}", &self.name, err);
+                    self.err();
+                }
+                Ok(_) => unreachable!(),
+            },
+            (State::Init, kind) => {
+                println!(
+                    "client {}: unknown message type '{}' when waiting for `ready`",
+                    &self.name, kind
+                );
+                self.err();
+            }
+            (State::Step, kind) => {
+                println!(
+                    "client {}: unknown message type '{}' when waiting for a move",
+                    &self.name, kind
+                );
+                self.err();
+            }
+            _ => unreachable!(),
+        }
+    }
+
     pub(crate) fn get_num(&mut self) -> u32 {
         self.num
     }
 
-    pub(crate) fn send_end(&mut self) {
+    pub(crate) async fn send_end(&mut self) {
         if self.is_err() {
             return;
         }
-        self.send_line(b"end\n".to_vec());
+        let line = match self.protocol {
+            Protocol::Legacy => b"end\n".to_vec(),
+            Protocol::Json => Self::encode_json(&ServerMessage::End),
+        };
+        self.send_line(line).await;
         self.state = State::End;
     }
 
-    pub(crate) fn send_game(&mut self) {
+    pub(crate) async fn send_game(&mut self) {
         if self.is_err() {
             return;
         }
-        self.send_line(b"game\n".to_vec());
+        let line = match self.protocol {
+            Protocol::Legacy => b"game\n".to_vec(),
+            Protocol::Json => Self::encode_json(&ServerMessage::Move),
+        };
+        self.send_line(line).await;
         self.state = State::Step;
     }
 
-    pub(crate) fn send_nums(&mut self, num: &[u32]) {
+    pub(crate) async fn send_nums(&mut self, num: &[u32]) {
         if self.is_err() {
             return;
         }
-        let mut buf = Vec::new();
-        for x in num {
-            if !buf.is_empty() {
-                buf.push(b' ');
+        let line = match self.protocol {
+            Protocol::Legacy => {
+                let mut buf = Vec::new();
+                for x in num {
+                    if !buf.is_empty() {
+                        buf.push(b' ');
+                    }
+                    write!(buf, "{}", x).unwrap();
+                }
+                write!(buf, "\n").unwrap();
+                buf
             }
-            write!(buf, "{}", x).unwrap();
-        }
-        write!(buf, "\n").unwrap();
+            Protocol::Json => Self::encode_json(&ServerMessage::Result {
+                nums: num.to_vec(),
+            }),
+        };
         self.state = State::Wait;
-        self.send_line(buf);
+        self.send_line(line).await;
     }
 
-    fn send_line(&mut self, line: Vec<u8>) {
-        let done = Arc::new(AtomicBool::new(false));
-        let err = Arc::new(AtomicBool::new(false));
-        let name = self.name.clone();
-        let stdin = Arc::clone(&self.stdin);
-        {
-            let done = Arc::clone(&done);
-            let err = Arc::clone(&err);
-            std::thread::spawn(move || {
-                let mut stdin = stdin.lock().unwrap();
-                let mut is_err = false;
-                if let Err(err) = stdin.write_all(&line) {
-                    eprintln!("client {}: failed to write line: {}", name, err);
-                    is_err = true;
-                } else if let Err(err) = stdin.flush() {
-                    eprintln!("client {}: failed to flush: {}", name, err);
-                    is_err = true;
-                }
-                if is_err {
-                    err.store(true, Ordering::SeqCst);
-                }
-                done.store(true, Ordering::SeqCst);
-            });
-        }
+    /// Sends the initial handshake message for [`Protocol::Json`] clients.
+    async fn send_hello(&mut self) {
+        let line = Self::encode_json(&ServerMessage::Hello {
+            protocol: JSON_PROTOCOL_VERSION,
+            game: self.game.name(),
+        });
+        self.send_line(line).await;
+    }
 
-        let deadline = std::time::Instant::now() + std::time::Duration::from_millis(100);
-        loop {
-            if std::time::Instant::now() > deadline {
-                eprintln!("client {}: send_line: timeout", &self.name);
+    fn encode_json<T: Serialize>(msg: &T) -> Vec<u8> {
+        let mut buf = serde_json::to_vec(msg).unwrap();
+        buf.push(b'\n');
+        buf
+    }
+
+    async fn send_line(&mut self, line: Vec<u8>) {
+        let stdin = Arc::clone(&self.stdin);
+        let write = async move {
+            let mut stdin = stdin.lock().await;
+            stdin.write_all(&line).await?;
+            stdin.flush().await?;
+            Ok::<_, std::io::Error>(())
+        };
+        match tokio::time::timeout(Duration::from_millis(100), write).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                eprintln!("client {}: failed to write line: {}", &self.name, err);
                 self.err();
-                return;
-            }
-            if !done.load(Ordering::SeqCst) {
-                std::thread::sleep(std::time::Duration::from_millis(30));
-                continue;
             }
-            if err.load(Ordering::SeqCst) {
+            Err(_) => {
+                eprintln!("client {}: send_line: timeout", &self.name);
                 self.err();
             }
-            return;
         }
     }
 }
@@ -0,0 +1,151 @@
+use anyhow::{bail, Result};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A single move submitted by a client during a round. All currently shipped
+/// games encode their moves as a single number, so the wire format in
+/// `client.rs` doesn't need to change per game.
+pub(crate) type Move = u32;
+
+/// Describes what clients are being asked to do this round.
+pub(crate) struct Message(pub(crate) &'static str);
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RoundOutcome {
+    pub(crate) winners: Vec<usize>,
+}
+
+/// A set of rules the engine can host. Selected by the `game` field in
+/// `Config` and shared by every `Client` in the match.
+pub(crate) trait Game: Send + Sync + fmt::Debug {
+    /// Name used in `Config::game` and in the protocol handshake.
+    fn name(&self) -> &'static str;
+    /// Message describing what clients are being asked to do this round.
+    fn prompt(&self) -> Message;
+    /// Parses a client's raw move token into a `Move`.
+    fn validate_move(&self, raw: &str) -> Result<Move>;
+    /// Decides which client indices won the round, given every client's move
+    /// in client order. Clients that errored out (crashed, timed out, sent
+    /// garbage) are represented by `None` and can never win a round,
+    /// regardless of how a particular game orders its candidates.
+    fn resolve(&self, moves: &[Option<Move>]) -> RoundOutcome;
+}
+
+/// Looks up a `Game` implementation by the name used in `Config::game`.
+pub(crate) fn by_name(name: &str) -> Result<Box<dyn Game>> {
+    match name {
+        "lowest-unique" => Ok(Box::new(LowestUnique)),
+        "auction" => Ok(Box::new(Auction)),
+        other => bail!(
+            "unknown game '{}' (known games: lowest-unique, auction)",
+            other
+        ),
+    }
+}
+
+/// Every client picks a number; whoever picked the lowest number nobody else
+/// also picked wins. This is the original, and only, rule the engine shipped
+/// with.
+#[derive(Debug)]
+struct LowestUnique;
+
+impl Game for LowestUnique {
+    fn name(&self) -> &'static str {
+        "lowest-unique"
+    }
+
+    fn prompt(&self) -> Message {
+        Message("pick the lowest number that no one else picks")
+    }
+
+    fn validate_move(&self, raw: &str) -> Result<Move> {
+        Ok(raw.parse()?)
+    }
+
+    fn resolve(&self, moves: &[Option<Move>]) -> RoundOutcome {
+        winners_by_uniqueness(moves, u32::cmp)
+    }
+}
+
+/// Every client submits a bid; whoever bid the highest number nobody else
+/// also bid wins. A toy sealed-bid auction, included to prove the `Game`
+/// abstraction hosts more than one rule set.
+#[derive(Debug)]
+struct Auction;
+
+impl Game for Auction {
+    fn name(&self) -> &'static str {
+        "auction"
+    }
+
+    fn prompt(&self) -> Message {
+        Message("submit the highest bid that no one else submits")
+    }
+
+    fn validate_move(&self, raw: &str) -> Result<Move> {
+        Ok(raw.parse()?)
+    }
+
+    fn resolve(&self, moves: &[Option<Move>]) -> RoundOutcome {
+        winners_by_uniqueness(moves, |a, b| b.cmp(a))
+    }
+}
+
+/// Shared "drop duplicates, then rank what's left" resolution used by both
+/// `LowestUnique` and `Auction` - they differ only in which end of the
+/// ranking wins. `None` entries (errored clients) never make it into the
+/// candidate pool, so they can never win regardless of `order`.
+fn winners_by_uniqueness(moves: &[Option<Move>], order: impl Fn(&Move, &Move) -> Ordering) -> RoundOutcome {
+    let mut set_used = std::collections::HashSet::new();
+    let mut set_loose = std::collections::HashSet::new();
+    for x in moves.iter().flatten() {
+        if !set_used.insert(x) {
+            set_loose.insert(x);
+        }
+    }
+    let mut winners: Vec<_> = moves
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, val)| val.as_ref().map(|val| (pos, val)))
+        .filter(|(_pos, val)| !set_loose.contains(val))
+        .collect();
+    winners.sort_by(|(_pos, a), (_pos2, b)| order(a, b));
+    RoundOutcome {
+        winners: winners.into_iter().map(|(pos, _val)| pos).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowest_unique_ignores_errored_clients() {
+        // Client #3 errored out (`None`); client #2's `3` is the only unique
+        // value among the remaining moves, so it is the sole winner and the
+        // errored client is not listed at all.
+        let outcome = LowestUnique.resolve(&[Some(5), Some(5), Some(3), None]);
+        assert_eq!(outcome.winners, vec![2]);
+    }
+
+    #[test]
+    fn auction_never_lets_an_errored_client_win() {
+        // Regression test: an errored client used to be represented by
+        // `u32::MAX`, which sorted first under `Auction`'s descending order
+        // and won outright since it was the only value of its kind.
+        let outcome = Auction.resolve(&[Some(5), Some(5), Some(3), None]);
+        assert_eq!(outcome.winners, vec![2]);
+    }
+
+    #[test]
+    fn all_errored_yields_no_winners() {
+        let outcome = Auction.resolve(&[None, None]);
+        assert!(outcome.winners.is_empty());
+    }
+}
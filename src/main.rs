@@ -1,10 +1,13 @@
 mod cfg;
 mod client;
+mod game;
 mod runner;
 
 use anyhow::{bail, Context, Result};
 use cfg::Config;
 use client::Client;
+use game::{Game, RoundOutcome};
+use std::sync::Arc;
 
 fn load_cfg() -> Result<Config> {
     let cfg_path = match std::env::args_os().nth(1) {
@@ -18,26 +21,36 @@ fn load_cfg() -> Result<Config> {
 struct State {
     clients: Vec<Client>,
 }
-fn main() -> Result<()> {
+#[tokio::main]
+async fn main() -> Result<()> {
     if std::env::var("__RUN__").is_ok() {
         return runner::runner_main()
     }
     println!("loading config");
     let config = load_cfg().context("failed to load config")?;
+    let game: Arc<dyn Game> = Arc::from(game::by_name(&config.game).context("unknown game")?);
     println!("Spawning clients");
     let mut clients = vec![];
     for program_path in &config.programs {
         clients.push(
-            client::Client::new(program_path, config.image.as_deref())
-                .context("internal error when spawning bot")?,
+            client::Client::new(
+                program_path,
+                config.image.as_deref(),
+                config.protocol,
+                &config.sandbox,
+                &config.runners,
+                Arc::clone(&game),
+            )
+            .await
+            .context("internal error when spawning bot")?,
         );
     }
     let mut score = vec![0; clients.len()];
     let mut state = State { clients };
-    wait_ready(&mut state);
+    wait_ready(&mut state).await;
     for i in 0..config.rounds {
-        println!("Round #{}", i);
-        let outcome = play_round(&mut state);
+        println!("Round #{} ({})", i, game.prompt());
+        let outcome = play_round(&mut state, game.as_ref()).await;
         match outcome.winners.get(0) {
             Some(&winner) => {
                 println!("winner is client #{}", winner);
@@ -49,7 +62,7 @@ fn main() -> Result<()> {
         }
     }
     for i in 0..state.clients.len() {
-        state.clients[i].send_end();
+        state.clients[i].send_end().await;
         println!(
             "Client #{} ({}) - {} points",
             i,
@@ -60,45 +73,38 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn wait_ready(state: &mut State) {
+async fn wait_ready(state: &mut State) {
     println!("waiting for readiness");
     for client in &mut state.clients {
-        client.poll();
+        client.poll().await;
         if client.is_init() {
             println!("client {} still initializing", client);
         }
     }
     println!("wait done");
 }
-#[derive(Debug)]
-struct RoundOutcome {
-    winners: Vec<usize>,
-}
 
-fn play_round(state: &mut State) -> RoundOutcome {
-    let mut nums = vec![];
-    for client in &mut state.clients {
-        client.send_game();
-        client.poll();
-        let num = client.get_num();
-        nums.push(num);
-    }
+async fn play_round(state: &mut State, game: &dyn Game) -> RoundOutcome {
+    // Run every client's think phase concurrently so the round's wall-clock
+    // time is bounded by the slowest single bot instead of the sum of all of
+    // them; each `poll()` still enforces its own per-client deadline.
+    let nums = futures::future::join_all(state.clients.iter_mut().map(|client| async move {
+        client.send_game().await;
+        client.poll().await;
+        client.get_num()
+    }))
+    .await;
     for client in &mut state.clients {
-        client.send_nums(&nums);
-    }
-    let mut set_used = std::collections::HashSet::new();
-    let mut set_loose = std::collections::HashSet::new();
-    for x in &nums {
-        if !set_used.insert(x) {
-            set_loose.insert(x);
-        }
+        client.send_nums(&nums).await;
     }
-    let mut winners: Vec<_> = nums
+    // Errored clients (crashed, timed out, sent garbage) must never win a
+    // round, so their move is hidden from `Game::resolve` entirely instead of
+    // relying on a sentinel value that happens to sort last.
+    let moves: Vec<_> = state
+        .clients
         .iter()
-        .enumerate()
-        .filter(|(_pos, val)| !set_loose.contains(val))
+        .zip(&nums)
+        .map(|(client, &num)| if client.is_err() { None } else { Some(num) })
         .collect();
-    winners.sort_by_key(|(_pos, val)| *val);
-    let winners = winners.into_iter().map(|(pos, _val)| pos).collect();
-    RoundOutcome { winners }
+    game.resolve(&moves)
 }
@@ -0,0 +1,110 @@
+use crate::cfg::{Runner, Runners};
+use anyhow::{bail, Context, Result};
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+    process::Command,
+};
+
+/// Loads the interpreter map from `__RUNNERS__` (set by `Client::new_on_host`
+/// when spawning this binary in `__RUN__` mode), falling back to the
+/// built-in defaults when it's absent, e.g. when `__RUN__` is set by hand for
+/// testing.
+fn load_runners() -> Runners {
+    match std::env::var("__RUNNERS__") {
+        Ok(raw) => match serde_json::from_str(&raw) {
+            Ok(runners) => runners,
+            Err(err) => {
+                eprintln!("__RUNNERS__ is set but not valid json: {}", err);
+                crate::cfg::default_runners()
+            }
+        },
+        Err(_) => crate::cfg::default_runners(),
+    }
+}
+
+/// Reads the first line of `path` and, if it's a shebang, returns a `Runner`
+/// whose `run` is the argv it names followed by `{script}` (e.g.
+/// `#!/usr/bin/env python3` -> `["python3", "{script}"]`).
+fn shebang_runner(path: &Path) -> Option<Runner> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut first_line = String::new();
+    BufReader::new(file).read_line(&mut first_line).ok()?;
+    let rest = first_line.trim().strip_prefix("#!")?;
+    let mut parts = rest.split_whitespace().map(str::to_string);
+    let mut argv: Vec<String> = match parts.next()?.as_str() {
+        "/usr/bin/env" => parts.collect(),
+        interpreter => std::iter::once(interpreter.to_string()).chain(parts).collect(),
+    };
+    if argv.is_empty() {
+        return None;
+    }
+    argv.push("{script}".to_string());
+    Some(Runner { build: None, run: argv })
+}
+
+fn detect_runner(path: &Path, runners: &Runners) -> Result<Runner> {
+    if let Some(ext) = path.extension().and_then(std::ffi::OsStr::to_str) {
+        if let Some(runner) = runners.get(ext) {
+            return Ok(runner.clone());
+        }
+    }
+    if let Some(runner) = shebang_runner(path) {
+        return Ok(runner);
+    }
+    bail!(
+        "could not detect how to run {} (known extensions: {})",
+        path.display(),
+        runners.keys().cloned().collect::<Vec<_>>().join(", ")
+    );
+}
+
+/// Substitutes `{script}`/`{out}` into an argv template and returns the result.
+fn render(template: &[String], script: &Path, out: &Path) -> Vec<String> {
+    template
+        .iter()
+        .map(|arg| {
+            arg.replace("{script}", &script.to_string_lossy())
+                .replace("{out}", &out.to_string_lossy())
+        })
+        .collect()
+}
+
+fn run_argv(argv: &[String]) -> Result<()> {
+    let (program, args) = argv.split_first().context("empty command")?;
+    let st = Command::new(program)
+        .args(args)
+        .status()
+        .with_context(|| format!("failed to launch {}", program))?;
+    if !st.success() {
+        bail!("command {:?} failed: {}", argv, st);
+    }
+    Ok(())
+}
+
+fn exec(path: &Path, runner: &Runner) -> Result<()> {
+    let out = path.with_extension("out");
+    if let Some(build) = &runner.build {
+        run_argv(&render(build, path, &out)).context("build step failed")?;
+    }
+    run_argv(&render(&runner.run, path, &out))
+}
+
+/// Entry point for the `__RUN__=1` re-exec used to run a bot on the host (see
+/// `Client::new_on_host`). `Config::runners` is threaded through as the
+/// `__RUNNERS__` environment variable, since this is a separate process with
+/// no access to the parent's `Config`.
+pub(crate) fn runner_main() -> Result<()> {
+    let path = match std::env::args_os().nth(1) {
+        None => {
+            eprintln!("path to file executed not given");
+            std::process::exit(1);
+        }
+        Some(x) => std::path::PathBuf::from(x),
+    };
+    let runners = load_runners();
+    let runner = detect_runner(&path, &runners)?;
+    eprintln!("{} will run via {:?}", path.display(), runner.run);
+    exec(&path, &runner)?;
+    Ok(())
+}